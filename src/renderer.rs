@@ -1,5 +1,11 @@
 use crate::camera::CameraWrapper;
 use cgmath;
+use cgmath::SquareMatrix;
+use ordered_float::OrderedFloat;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
 use std::time;
 use wgpu;
 use winit::{
@@ -10,6 +16,8 @@ use winit::{
 
 static DEFAULT_MESH_RESOLUTION: u16 = 16;
 
+pub type IVec3 = cgmath::Vector3<i32>;
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 #[allow(unused)]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
@@ -23,6 +31,76 @@ const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
 const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
 const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
 
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const DESIRED_SAMPLE_COUNT: u32 = 4;
+
+fn create_depth_view(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth"),
+        size: wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+    });
+    depth_texture.create_default_view()
+}
+
+fn create_msaa_view(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let msaa_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa"),
+        size: wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: sc_desc.format,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+    });
+    msaa_texture.create_default_view()
+}
+
+/// Returns `desired` if this device can actually create a multisampled
+/// render target with that many samples, and 1 otherwise; no count between
+/// the two is tried. GL backends frequently can't resolve a multisampled
+/// target into the swap chain, so they skip MSAA outright; everywhere else we try creating the depth/MSAA
+/// attachments at `desired` under an error scope and fall back to no AA if
+/// the device rejects it.
+async fn choose_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    desired: u32,
+) -> u32 {
+    if adapter.get_info().backend == wgpu::Backend::Gl {
+        return 1;
+    }
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let _ = create_depth_view(device, sc_desc, desired);
+    let _ = create_msaa_view(device, sc_desc, desired);
+    match device.pop_error_scope().await {
+        Some(_) => 1,
+        None => desired,
+    }
+}
+
 pub async fn run_async(event_loop: EventLoop<()>, window: Window) {
     log::info!("Initializing the surface...");
 
@@ -65,8 +143,10 @@ pub async fn run_async(event_loop: EventLoop<()>, window: Window) {
     };
     let mut swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
+    let sample_count = choose_sample_count(&adapter, &device, &sc_desc, DESIRED_SAMPLE_COUNT).await;
+
     log::info!("Initializing the Renderer...");
-    let mut renderer = Renderer::init(&sc_desc, &device, DEFAULT_MESH_RESOLUTION);
+    let mut renderer = Renderer::init(&sc_desc, &device, DEFAULT_MESH_RESOLUTION, sample_count);
 
     let mut last_update_inst = time::Instant::now();
 
@@ -107,8 +187,21 @@ pub async fn run_async(event_loop: EventLoop<()>, window: Window) {
                 | WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::E),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    if let Err(err) = renderer.export_obj(Path::new("voxels.obj")) {
+                        log::error!("Failed to export voxels.obj: {:?}", err);
+                    }
+                }
                 _ => {
-                    renderer.update(event, &sc_desc, &queue);
+                    renderer.update(event, &sc_desc, &device, &queue);
                 }
             },
             event::Event::RedrawRequested(_) => {
@@ -225,6 +318,219 @@ struct Pipeline {
     index_count: usize,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CubeVertex {
+    _pos: [f32; 3],
+    _normal: [f32; 3],
+}
+
+unsafe impl Pod for CubeVertex {}
+unsafe impl Zeroable for CubeVertex {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CubeInstance {
+    _translation: [f32; 3],
+    _color: [f32; 4],
+}
+
+unsafe impl Pod for CubeInstance {}
+unsafe impl Zeroable for CubeInstance {}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightUniform {
+    _position: [f32; 4],
+    _color: [f32; 4],
+    _view_pos: [f32; 4],
+}
+
+unsafe impl Pod for LightUniform {}
+unsafe impl Zeroable for LightUniform {}
+
+fn cube_vertex(pos: [f32; 3], normal: [f32; 3]) -> CubeVertex {
+    CubeVertex { _pos: pos, _normal: normal }
+}
+
+// The six faces of a unit cube spanning [0,1]^3, each with its outward
+// normal and four corners in counter-clockwise winding order. Shared by the
+// cube mesh generator and the OBJ exporter's greedy face culling.
+const CUBE_FACES: [([f32; 3], [[f32; 3]; 4]); 6] = [
+    // front (+z)
+    ([0.0, 0.0, 1.0], [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]]),
+    // back (-z)
+    ([0.0, 0.0, -1.0], [[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]]),
+    // right (+x)
+    ([1.0, 0.0, 0.0], [[1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0]]),
+    // left (-x)
+    ([-1.0, 0.0, 0.0], [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0]]),
+    // top (+y)
+    ([0.0, 1.0, 0.0], [[0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]),
+    // bottom (-y)
+    ([0.0, -1.0, 0.0], [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]]),
+];
+
+// A 24-vertex (4 per face) unit cube so each face keeps its own vertices and
+// its own outward-facing normal for flat, per-face Blinn-Phong shading.
+fn generate_cube_vertices() -> (Vec<CubeVertex>, Vec<u16>) {
+    let mut vertex_data = Vec::with_capacity(24);
+    let mut index_data = Vec::with_capacity(36);
+    for (face, (normal, corners)) in CUBE_FACES.iter().enumerate() {
+        let base = (face * 4) as u16;
+        for corner in corners {
+            vertex_data.push(cube_vertex(*corner, *normal));
+        }
+        index_data.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertex_data, index_data)
+}
+
+const PLACE_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const MAX_RAY_STEPS: i32 = 256;
+
+/// Unprojects a point given in normalized device coordinates into a
+/// world-space ray, given the inverse of the camera's MVP matrix. The ray
+/// origin is only an arbitrary point along the ray, not the camera's eye —
+/// use `ndc_eye` for that.
+fn ndc_to_ray(
+    inv_mvp: cgmath::Matrix4<f32>,
+    ndc_x: f32,
+    ndc_y: f32,
+) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+    use cgmath::{InnerSpace, Point3};
+
+    let unproject = |ndc_z: f32| -> Point3<f32> {
+        let clip = cgmath::Vector4::new(ndc_x, ndc_y, ndc_z, 1.0);
+        let world = inv_mvp * clip;
+        Point3::new(world.x / world.w, world.y / world.w, world.z / world.w)
+    };
+
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+    let direction = (far - near).normalize();
+    (near, direction)
+}
+
+/// Unprojects the clip-space point `(0, 0, 1, 0)`, which is where a
+/// perspective projection sends the eye (`w = 0`), to recover the camera's
+/// world-space eye position, given the inverse of the camera's MVP matrix.
+fn ndc_eye(inv_mvp: cgmath::Matrix4<f32>) -> cgmath::Point3<f32> {
+    let clip = cgmath::Vector4::new(0.0, 0.0, 1.0, 0.0);
+    let world = inv_mvp * clip;
+    cgmath::Point3::new(world.x / world.w, world.y / world.w, world.z / world.w)
+}
+
+/// Unprojects a cursor position (in pixels) into a world-space ray, given
+/// the inverse of the camera's MVP matrix.
+fn unproject_ray(
+    inv_mvp: cgmath::Matrix4<f32>,
+    cursor: (f64, f64),
+    width: f32,
+    height: f32,
+) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+    let ndc_x = 2.0 * cursor.0 as f32 / width - 1.0;
+    let ndc_y = 1.0 - 2.0 * cursor.1 as f32 / height;
+    ndc_to_ray(inv_mvp, ndc_x, ndc_y)
+}
+
+fn sign(v: f32) -> i32 {
+    if v > 0.0 {
+        1
+    } else if v < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Marches `origin + t * dir` through the integer voxel grid using the
+/// Amanatides-Woo 3D-DDA algorithm, stopping at the first occupied voxel.
+/// Returns the hit voxel, the normal of the face that was entered, and the
+/// empty cell adjacent to that face (where a new voxel would be placed).
+fn cast_voxel_ray(
+    origin: cgmath::Point3<f32>,
+    dir: cgmath::Vector3<f32>,
+    is_occupied: impl Fn(IVec3) -> bool,
+) -> Option<(IVec3, IVec3, IVec3)> {
+    let mut voxel = IVec3::new(
+        origin.x.floor() as i32,
+        origin.y.floor() as i32,
+        origin.z.floor() as i32,
+    );
+    let step = IVec3::new(sign(dir.x), sign(dir.y), sign(dir.z));
+
+    let t_delta = cgmath::Vector3::new(
+        if dir.x != 0.0 { (1.0 / dir.x).abs() } else { f32::INFINITY },
+        if dir.y != 0.0 { (1.0 / dir.y).abs() } else { f32::INFINITY },
+        if dir.z != 0.0 { (1.0 / dir.z).abs() } else { f32::INFINITY },
+    );
+
+    let axis_t_max = |o: f32, v: i32, s: i32, d: f32| -> f32 {
+        if s > 0 {
+            ((v as f32 + 1.0) - o) / d
+        } else if s < 0 {
+            (v as f32 - o) / d
+        } else {
+            f32::INFINITY
+        }
+    };
+    let mut t_max = cgmath::Vector3::new(
+        axis_t_max(origin.x, voxel.x, step.x, dir.x),
+        axis_t_max(origin.y, voxel.y, step.y, dir.y),
+        axis_t_max(origin.z, voxel.z, step.z, dir.z),
+    );
+
+    let mut place = voxel;
+    let mut normal = IVec3::new(0, 0, 0);
+
+    for _ in 0..MAX_RAY_STEPS {
+        if is_occupied(voxel) {
+            return Some((voxel, normal, place));
+        }
+        place = voxel;
+
+        if t_max.x < t_max.y && t_max.x < t_max.z {
+            voxel.x += step.x;
+            t_max.x += t_delta.x;
+            normal = IVec3::new(-step.x, 0, 0);
+        } else if t_max.y < t_max.z {
+            voxel.y += step.y;
+            t_max.y += t_delta.y;
+            normal = IVec3::new(0, -step.y, 0);
+        } else {
+            voxel.z += step.z;
+            t_max.z += t_delta.z;
+            normal = IVec3::new(0, 0, -step.z);
+        }
+    }
+
+    None
+}
+
+/// Intersects `origin + t * dir` with the ground plane `y = 0` and returns
+/// the voxel cell sitting on it. Used as a placement fallback when
+/// `cast_voxel_ray` finds no occupied voxel to hit, which would otherwise
+/// leave an empty scene with no way to place the first voxel. Only hits in
+/// front of the ray (`t > 0`) going downward count, so looking up or away
+/// from the ground yields no placement.
+fn ground_plane_cell(
+    origin: cgmath::Point3<f32>,
+    dir: cgmath::Vector3<f32>,
+) -> Option<IVec3> {
+    if dir.y >= 0.0 {
+        return None;
+    }
+    let t = -origin.y / dir.y;
+    if t <= 0.0 {
+        return None;
+    }
+    let x = origin.x + t * dir.x;
+    let z = origin.z + t * dir.z;
+    Some(IVec3::new(x.floor() as i32, 0, z.floor() as i32))
+}
+
 impl Pipeline {
     fn draw<'a>(
         &'a mut self,
@@ -238,10 +544,43 @@ impl Pipeline {
     }
 }
 
+struct CubePipeline {
+    bind_group: wgpu::BindGroup,
+    uniform_buf: wgpu::Buffer,
+    light_uniform_buf: wgpu::Buffer,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buf: wgpu::Buffer,
+    index_buf: wgpu::Buffer,
+    instance_buf: wgpu::Buffer,
+    instance_count: usize,
+}
+
+impl CubePipeline {
+    fn draw<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.instance_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_index_buffer(self.index_buf.slice(..));
+        render_pass.set_vertex_buffer(0, self.vertex_buf.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buf.slice(..));
+        render_pass.draw_indexed(0..36, 0, 0..self.instance_count as u32);
+    }
+}
+
 pub struct Renderer {
     camera: CameraWrapper,
     mesh_pipeline: Pipeline,
+    cube_pipeline: CubePipeline,
     _mesh_resolution: u16,
+    depth_view: wgpu::TextureView,
+    msaa_view: wgpu::TextureView,
+    sample_count: u32,
+    voxels: HashMap<IVec3, [f32; 4]>,
+    cursor_pos: (f64, f64),
+    light_pos: [f32; 4],
+    light_color: [f32; 4],
 }
 
 impl Renderer {
@@ -249,6 +588,7 @@ impl Renderer {
         sc_desc: &wgpu::SwapChainDescriptor,
         device: &wgpu::Device,
         mesh_resolution: u16,
+        sample_count: u32,
     ) -> Self {
         use std::mem;
 
@@ -332,7 +672,12 @@ impl Renderer {
                 alpha_blend: wgpu::BlendDescriptor::REPLACE,
                 write_mask: wgpu::ColorWrite::ALL,
             }],
-            depth_stencil_state: None,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilStateDescriptor::default(),
+            }),
             vertex_state: wgpu::VertexStateDescriptor {
                 index_format: wgpu::IndexFormat::Uint16,
                 vertex_buffers: &[wgpu::VertexBufferDescriptor {
@@ -354,11 +699,171 @@ impl Renderer {
                     ],
                 }],
             },
-            sample_count: 1,
+            sample_count,
             sample_mask: !0,
             alpha_to_coverage_enabled: false,
         });
 
+        // Create the cube vertex/index buffers and the per-instance buffer that
+        // carries each voxel's translation and color.
+        let cube_vertex_size = mem::size_of::<CubeVertex>();
+        let instance_size = mem::size_of::<CubeInstance>();
+        let (cube_vertex_data, cube_index_data) = generate_cube_vertices();
+
+        let cube_vertex_buf = device.create_buffer_with_data(
+            bytemuck::cast_slice(&cube_vertex_data),
+            wgpu::BufferUsage::VERTEX,
+        );
+        let cube_index_buf = device.create_buffer_with_data(
+            bytemuck::cast_slice(&cube_index_data),
+            wgpu::BufferUsage::INDEX,
+        );
+        let cube_instance_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("voxel instances"),
+            size: 0,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cube_uniform_buf = device.create_buffer_with_data(
+            bytemuck::cast_slice(mx_ref),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        // Second bind-group entry: the light and the eye (view) position the
+        // fragment shader needs for the Blinn-Phong specular term.
+        let light_uniform = LightUniform {
+            _position: [2.0, 3.0, 2.0, 1.0],
+            _color: [1.0, 1.0, 1.0, 1.0],
+            _view_pos: [0.0, 0.0, 0.0, 1.0],
+        };
+        let light_uniform_buf = device.create_buffer_with_data(
+            bytemuck::bytes_of(&light_uniform),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let cube_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                bindings: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    },
+                ],
+            });
+        let cube_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&cube_bind_group_layout],
+        });
+        let cube_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cube_bind_group_layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(cube_uniform_buf.slice(..)),
+                },
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(light_uniform_buf.slice(..)),
+                },
+            ],
+            label: None,
+        });
+
+        let cube_vs_bytes = include_bytes!("cube.vert.spv");
+        let cube_fs_bytes = include_bytes!("cube.frag.spv");
+        let cube_vs_module = device.create_shader_module(
+            &wgpu::read_spirv(std::io::Cursor::new(&cube_vs_bytes[..])).unwrap(),
+        );
+        let cube_fs_module = device.create_shader_module(
+            &wgpu::read_spirv(std::io::Cursor::new(&cube_fs_bytes[..])).unwrap(),
+        );
+
+        let cube_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: &cube_pipeline_layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &cube_vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &cube_fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: sc_desc.format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilStateDescriptor::default(),
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[
+                    wgpu::VertexBufferDescriptor {
+                        stride: cube_vertex_size as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Vertex,
+                        attributes: &[
+                            // Position
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            // Normal
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float3,
+                                offset: 3 * 4,
+                                shader_location: 3,
+                            },
+                        ],
+                    },
+                    wgpu::VertexBufferDescriptor {
+                        stride: instance_size as wgpu::BufferAddress,
+                        step_mode: wgpu::InputStepMode::Instance,
+                        attributes: &[
+                            // Instance translation
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float3,
+                                offset: 0,
+                                shader_location: 1,
+                            },
+                            // Instance color
+                            wgpu::VertexAttributeDescriptor {
+                                format: wgpu::VertexFormat::Float4,
+                                offset: 3 * 4,
+                                shader_location: 2,
+                            },
+                        ],
+                    },
+                ],
+            },
+            sample_count,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+
+        let depth_view = create_depth_view(device, sc_desc, sample_count);
+        let msaa_view = create_msaa_view(device, sc_desc, sample_count);
+
         // Done
         Renderer {
             camera,
@@ -370,31 +875,280 @@ impl Renderer {
                 index_buf: index_buf_mesh,
                 index_count: index_data.len(),
             },
+            cube_pipeline: CubePipeline {
+                pipeline: cube_pipeline,
+                bind_group: cube_bind_group,
+                uniform_buf: cube_uniform_buf,
+                light_uniform_buf,
+                vertex_buf: cube_vertex_buf,
+                index_buf: cube_index_buf,
+                instance_buf: cube_instance_buf,
+                instance_count: 0,
+            },
             _mesh_resolution: mesh_resolution,
+            depth_view,
+            msaa_view,
+            sample_count,
+            light_pos: light_uniform._position,
+            light_color: light_uniform._color,
+            voxels: HashMap::new(),
+            cursor_pos: (0.0, 0.0),
+        }
+    }
+
+    /// Repacks `voxels` into the instance buffer and uploads it, replacing
+    /// the set of cubes drawn by the cube pipeline.
+    pub fn set_voxels(&mut self, device: &wgpu::Device, voxels: &[(IVec3, [f32; 4])]) {
+        let instance_data: Vec<CubeInstance> = voxels
+            .iter()
+            .map(|(pos, color)| CubeInstance {
+                _translation: [pos.x as f32, pos.y as f32, pos.z as f32],
+                _color: *color,
+            })
+            .collect();
+
+        // wgpu rejects a zero-length mapped buffer, so an empty voxel set
+        // (e.g. after deleting the last voxel) needs the same unmapped,
+        // zero-size buffer `init` uses before any voxel is ever placed.
+        self.cube_pipeline.instance_buf = if instance_data.is_empty() {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("voxel instances"),
+                size: 0,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+                mapped_at_creation: false,
+            })
+        } else {
+            device.create_buffer_with_data(
+                bytemuck::cast_slice(&instance_data),
+                wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            )
+        };
+        self.cube_pipeline.instance_count = instance_data.len();
+    }
+
+    fn refresh_voxel_instances(&mut self, device: &wgpu::Device) {
+        let voxels: Vec<(IVec3, [f32; 4])> =
+            self.voxels.iter().map(|(pos, color)| (*pos, *color)).collect();
+        self.set_voxels(device, &voxels);
+    }
+
+    /// Casts a ray through `cursor` (window pixel coordinates) and returns
+    /// the first occupied voxel hit, its face normal, and the empty cell
+    /// adjacent to that face where a new voxel would be placed. If the ray
+    /// hits no voxel, falls back to the `y = 0` ground plane so the scene
+    /// is never a dead end with nothing to pick: `hit` and `place` are both
+    /// the ground cell, which is harmless for deletion (it's never in
+    /// `voxels`) and lets placement seed the very first voxel.
+    fn pick_voxel(
+        &mut self,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        cursor: (f64, f64),
+    ) -> Option<(IVec3, IVec3, IVec3)> {
+        let aspect = sc_desc.width as f32 / sc_desc.height as f32;
+        let inv_mvp = self.camera.mvp_matrix(aspect).invert()?;
+        let (origin, dir) =
+            unproject_ray(inv_mvp, cursor, sc_desc.width as f32, sc_desc.height as f32);
+
+        if let Some(hit) = cast_voxel_ray(origin, dir, |v| self.voxels.contains_key(&v)) {
+            return Some(hit);
+        }
+
+        let ground = ground_plane_cell(origin, dir)?;
+        Some((ground, IVec3::new(0, 1, 0), ground))
+    }
+
+    /// Moves the scene light, uploading its new position/color alongside the
+    /// camera's current eye position.
+    pub fn set_light(&mut self, sc_desc: &wgpu::SwapChainDescriptor, queue: &wgpu::Queue, position: [f32; 4], color: [f32; 4]) {
+        self.light_pos = position;
+        self.light_color = color;
+        self.write_light_uniform(sc_desc, queue);
+    }
+
+    fn write_light_uniform(&mut self, sc_desc: &wgpu::SwapChainDescriptor, queue: &wgpu::Queue) {
+        let aspect = sc_desc.width as f32 / sc_desc.height as f32;
+        let view_pos = match self.camera.mvp_matrix(aspect).invert() {
+            Some(inv_mvp) => {
+                let eye = ndc_eye(inv_mvp);
+                [eye.x, eye.y, eye.z, 1.0]
+            }
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+        let light_uniform = LightUniform {
+            _position: self.light_pos,
+            _color: self.light_color,
+            _view_pos: view_pos,
+        };
+        queue.write_buffer(
+            &self.cube_pipeline.light_uniform_buf,
+            0,
+            bytemuck::bytes_of(&light_uniform),
+        );
+    }
+
+    /// Writes the current voxel set to a triangulated Wavefront OBJ (plus a
+    /// companion MTL with one material per distinct voxel color), emitting
+    /// only the faces whose neighboring voxel is empty.
+    pub fn export_obj(&self, path: &Path) -> std::io::Result<()> {
+        let mtl_path = path.with_extension("mtl");
+        let mtl_name = mtl_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "voxels.mtl".to_string());
+
+        let mut obj = String::new();
+        obj.push_str(&format!("mtllib {}\n", mtl_name));
+
+        let mut vertex_index: HashMap<[OrderedFloat<f32>; 3], usize> = HashMap::new();
+        let mut vertices: Vec<[f32; 3]> = Vec::new();
+        let mut normal_index: HashMap<[OrderedFloat<f32>; 3], usize> = HashMap::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut materials: Vec<[f32; 4]> = Vec::new();
+        let mut material_index: HashMap<[OrderedFloat<f32>; 4], usize> = HashMap::new();
+        let mut faces_by_material: Vec<Vec<[(usize, usize); 4]>> = Vec::new();
+
+        for (pos, color) in &self.voxels {
+            let color_key = [
+                OrderedFloat(color[0]),
+                OrderedFloat(color[1]),
+                OrderedFloat(color[2]),
+                OrderedFloat(color[3]),
+            ];
+            let mat = *material_index.entry(color_key).or_insert_with(|| {
+                materials.push(*color);
+                faces_by_material.push(Vec::new());
+                materials.len() - 1
+            });
+
+            for (normal, corners) in CUBE_FACES.iter() {
+                let neighbor = IVec3::new(
+                    pos.x + normal[0] as i32,
+                    pos.y + normal[1] as i32,
+                    pos.z + normal[2] as i32,
+                );
+                if self.voxels.contains_key(&neighbor) {
+                    continue;
+                }
+
+                let normal_key =
+                    [OrderedFloat(normal[0]), OrderedFloat(normal[1]), OrderedFloat(normal[2])];
+                let ni = *normal_index.entry(normal_key).or_insert_with(|| {
+                    normals.push(*normal);
+                    normals.len() - 1
+                });
+
+                let mut face = [(0usize, 0usize); 4];
+                for (i, corner) in corners.iter().enumerate() {
+                    let world = [
+                        corner[0] + pos.x as f32,
+                        corner[1] + pos.y as f32,
+                        corner[2] + pos.z as f32,
+                    ];
+                    let vertex_key =
+                        [OrderedFloat(world[0]), OrderedFloat(world[1]), OrderedFloat(world[2])];
+                    let vi = *vertex_index.entry(vertex_key).or_insert_with(|| {
+                        vertices.push(world);
+                        vertices.len() - 1
+                    });
+                    face[i] = (vi, ni);
+                }
+                faces_by_material[mat].push(face);
+            }
+        }
+
+        for v in &vertices {
+            obj.push_str(&format!("v {} {} {}\n", v[0], v[1], v[2]));
+        }
+        for n in &normals {
+            obj.push_str(&format!("vn {} {} {}\n", n[0], n[1], n[2]));
+        }
+
+        let mut mtl = String::new();
+        for (i, faces) in faces_by_material.iter().enumerate() {
+            if faces.is_empty() {
+                continue;
+            }
+            let material_name = format!("voxel_{}", i);
+            obj.push_str(&format!("usemtl {}\n", material_name));
+            for face in faces {
+                // Split each quad into two triangles with the same winding
+                // as `generate_cube_vertices`.
+                for tri in &[[0, 1, 2], [0, 2, 3]] {
+                    obj.push_str("f");
+                    for &corner in tri {
+                        let (vi, ni) = face[corner];
+                        obj.push_str(&format!(" {}//{}", vi + 1, ni + 1));
+                    }
+                    obj.push_str("\n");
+                }
+            }
+
+            let color = materials[i];
+            mtl.push_str(&format!("newmtl {}\n", material_name));
+            mtl.push_str(&format!("Kd {} {} {}\n", color[0], color[1], color[2]));
+            mtl.push_str(&format!("d {}\n", color[3]));
         }
+
+        File::create(path)?.write_all(obj.as_bytes())?;
+        File::create(&mtl_path)?.write_all(mtl.as_bytes())?;
+        Ok(())
     }
 
     pub fn update(
         &mut self,
         event: winit::event::WindowEvent,
         sc_desc: &wgpu::SwapChainDescriptor,
+        device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_pos = (position.x, position.y);
+            }
+            WindowEvent::MouseInput {
+                state: event::ElementState::Pressed,
+                button,
+                ..
+            } => {
+                if let Some((hit, _normal, place)) = self.pick_voxel(sc_desc, self.cursor_pos) {
+                    match button {
+                        event::MouseButton::Left => {
+                            self.voxels.remove(&hit);
+                            self.refresh_voxel_instances(device);
+                        }
+                        event::MouseButton::Right => {
+                            self.voxels.insert(place, PLACE_COLOR);
+                            self.refresh_voxel_instances(device);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+
         self.camera.update(&event);
         let mx = self.camera.mvp_matrix(sc_desc.width as f32 / sc_desc.height as f32);
         let mx_ref = mx.as_ref();
         queue.write_buffer(&self.mesh_pipeline.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        queue.write_buffer(&self.cube_pipeline.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        self.write_light_uniform(sc_desc, queue);
     }
 
     pub fn resize(
         &mut self,
         sc_desc: &wgpu::SwapChainDescriptor,
-        _device: &wgpu::Device,
+        device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) {
+        self.depth_view = create_depth_view(device, sc_desc, self.sample_count);
+        self.msaa_view = create_msaa_view(device, sc_desc, self.sample_count);
+
         let mx = self.camera.mvp_matrix(sc_desc.width as f32 / sc_desc.height as f32);
         let mx_ref = mx.as_ref();
         queue.write_buffer(&self.mesh_pipeline.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        queue.write_buffer(&self.cube_pipeline.uniform_buf, 0, bytemuck::cast_slice(mx_ref));
+        self.write_light_uniform(sc_desc, queue);
     }
 
     pub fn render(
@@ -408,8 +1162,8 @@ impl Renderer {
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
-                    resolve_target: None,
+                    attachment: if self.sample_count > 1 { &self.msaa_view } else { &frame.view },
+                    resolve_target: if self.sample_count > 1 { Some(&frame.view) } else { None },
                     load_op: wgpu::LoadOp::Clear,
                     store_op: wgpu::StoreOp::Store,
                     clear_color: wgpu::Color {
@@ -419,9 +1173,18 @@ impl Renderer {
                         a: 1.0,
                     },
                 }],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &self.depth_view,
+                    depth_load_op: wgpu::LoadOp::Clear,
+                    depth_store_op: wgpu::StoreOp::Store,
+                    clear_depth: 1.0,
+                    stencil_load_op: wgpu::LoadOp::Clear,
+                    stencil_store_op: wgpu::StoreOp::Store,
+                    clear_stencil: 0,
+                }),
             });
             self.mesh_pipeline.draw(&mut rpass);
+            self.cube_pipeline.draw(&mut rpass);
         }
 
         encoder.finish()